@@ -0,0 +1,308 @@
+// backend/src/fs.rs
+//! Structured filesystem requests, as an alternative to the shell's
+//! `ls`/`cat` style text commands. Every path goes through the same
+//! sandbox-canonicalization rules as `cd` (see `ws_logic::resolve_*`), so
+//! this gives the frontend a file-manager capability without weakening the
+//! confinement `ws_logic` already enforces.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::ws_logic::{resolve_existing_in_sandbox, resolve_new_in_sandbox, to_absolute};
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum ClientRequest {
+    FileRead {
+        path: String,
+    },
+    FileWrite {
+        path: String,
+        data: String,
+        #[serde(default)]
+        append: bool,
+    },
+    Copy {
+        src: String,
+        dst: String,
+    },
+    Rename {
+        src: String,
+        dst: String,
+    },
+    MakeDir {
+        path: String,
+        #[serde(default)]
+        all: bool,
+    },
+    Remove {
+        path: String,
+        #[serde(default)]
+        recursive: bool,
+    },
+    Metadata {
+        path: String,
+    },
+    Watch {
+        path: String,
+        #[serde(default)]
+        recursive: bool,
+    },
+    Unwatch {
+        path: String,
+    },
+    Search {
+        pattern: String,
+        #[serde(default)]
+        content: bool,
+        #[serde(default)]
+        case_insensitive: bool,
+        #[serde(default)]
+        max_depth: Option<usize>,
+        #[serde(default)]
+        glob: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    OpenSession,
+    AttachSession {
+        id: String,
+    },
+    ListSessions,
+    CloseSession {
+        id: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum ServerResponse {
+    FileRead {
+        path: String,
+        data: String,
+    },
+    Metadata {
+        path: String,
+        file_type: &'static str,
+        len: u64,
+        readonly: bool,
+        modified: Option<u64>,
+        accessed: Option<u64>,
+    },
+    /// Pushed unsolicited whenever a watched path changes; never sent as a
+    /// direct reply to a request.
+    Change {
+        path: String,
+        kind: &'static str,
+    },
+    SessionOpened {
+        id: String,
+    },
+    SessionAttached {
+        id: String,
+        cwd: String,
+    },
+    SessionList {
+        sessions: Vec<crate::session::SessionSummary>,
+    },
+    Ok,
+    Error {
+        message: String,
+    },
+}
+
+/// Handles the file-operation `ClientRequest`s. `Watch`/`Unwatch` (which need
+/// the per-connection watcher registry in `AppState`), `Search` (which
+/// streams its matches) and the session requests (which change the
+/// connection's cwd) are handled directly by `ws_logic::handle_line_socket`;
+/// passed here, they get an `Error` response.
+pub async fn handle(request: &ClientRequest, current_dir: &Path) -> ServerResponse {
+    match request {
+        ClientRequest::FileRead { path } => read_file(path, current_dir).await,
+        ClientRequest::FileWrite { path, data, append } => {
+            write_file(path, data, *append, current_dir).await
+        }
+        ClientRequest::Copy { src, dst } => copy(src, dst, current_dir).await,
+        ClientRequest::Rename { src, dst } => rename(src, dst, current_dir).await,
+        ClientRequest::MakeDir { path, all } => make_dir(path, *all, current_dir).await,
+        ClientRequest::Remove { path, recursive } => remove(path, *recursive, current_dir).await,
+        ClientRequest::Metadata { path } => metadata(path, current_dir).await,
+        ClientRequest::Watch { .. } | ClientRequest::Unwatch { .. } => ServerResponse::Error {
+            message: "Watch/Unwatch must be handled by the connection's watcher registry"
+                .to_string(),
+        },
+        ClientRequest::Search { .. } => ServerResponse::Error {
+            message: "Search streams its matches and must be handled by ws_logic directly"
+                .to_string(),
+        },
+        ClientRequest::OpenSession
+        | ClientRequest::AttachSession { .. }
+        | ClientRequest::ListSessions
+        | ClientRequest::CloseSession { .. } => ServerResponse::Error {
+            message: "Session requests must be handled by ws_logic, which owns the connection's cwd"
+                .to_string(),
+        },
+    }
+}
+
+async fn read_file(path: &str, current_dir: &Path) -> ServerResponse {
+    let resolved = match resolve_existing_in_sandbox(path, current_dir) {
+        Ok(p) => p,
+        Err(message) => return ServerResponse::Error { message },
+    };
+    match tokio::fs::read_to_string(&resolved).await {
+        Ok(data) => ServerResponse::FileRead {
+            path: resolved.display().to_string(),
+            data,
+        },
+        Err(e) => ServerResponse::Error {
+            message: format!("Failed to read '{}': {}", path, e),
+        },
+    }
+}
+
+async fn write_file(path: &str, data: &str, append: bool, current_dir: &Path) -> ServerResponse {
+    let resolved = match resolve_new_in_sandbox(path, current_dir) {
+        Ok(p) => p,
+        Err(message) => return ServerResponse::Error { message },
+    };
+
+    use tokio::io::AsyncWriteExt;
+    let result = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&resolved)
+            .await?;
+        file.write_all(data.as_bytes()).await
+    }
+    .await;
+
+    match result {
+        Ok(()) => ServerResponse::Ok,
+        Err(e) => ServerResponse::Error {
+            message: format!("Failed to write '{}': {}", path, e),
+        },
+    }
+}
+
+async fn copy(src: &str, dst: &str, current_dir: &Path) -> ServerResponse {
+    let src_resolved = match resolve_existing_in_sandbox(src, current_dir) {
+        Ok(p) => p,
+        Err(message) => return ServerResponse::Error { message },
+    };
+    let dst_resolved = match resolve_new_in_sandbox(dst, current_dir) {
+        Ok(p) => p,
+        Err(message) => return ServerResponse::Error { message },
+    };
+    match tokio::fs::copy(&src_resolved, &dst_resolved).await {
+        Ok(_) => ServerResponse::Ok,
+        Err(e) => ServerResponse::Error {
+            message: format!("Failed to copy '{}' to '{}': {}", src, dst, e),
+        },
+    }
+}
+
+async fn rename(src: &str, dst: &str, current_dir: &Path) -> ServerResponse {
+    let src_resolved = match resolve_existing_in_sandbox(src, current_dir) {
+        Ok(p) => p,
+        Err(message) => return ServerResponse::Error { message },
+    };
+    let dst_resolved = match resolve_new_in_sandbox(dst, current_dir) {
+        Ok(p) => p,
+        Err(message) => return ServerResponse::Error { message },
+    };
+    match tokio::fs::rename(&src_resolved, &dst_resolved).await {
+        Ok(()) => ServerResponse::Ok,
+        Err(e) => ServerResponse::Error {
+            message: format!("Failed to rename '{}' to '{}': {}", src, dst, e),
+        },
+    }
+}
+
+async fn make_dir(path: &str, all: bool, current_dir: &Path) -> ServerResponse {
+    let resolved = match resolve_new_in_sandbox(path, current_dir) {
+        Ok(p) => p,
+        Err(message) => return ServerResponse::Error { message },
+    };
+    let result = if all {
+        tokio::fs::create_dir_all(&resolved).await
+    } else {
+        tokio::fs::create_dir(&resolved).await
+    };
+    match result {
+        Ok(()) => ServerResponse::Ok,
+        Err(e) => ServerResponse::Error {
+            message: format!("Failed to create directory '{}': {}", path, e),
+        },
+    }
+}
+
+async fn remove(path: &str, recursive: bool, current_dir: &Path) -> ServerResponse {
+    let resolved = match resolve_existing_in_sandbox(path, current_dir) {
+        Ok(p) => p,
+        Err(message) => return ServerResponse::Error { message },
+    };
+    let result = if resolved.is_dir() {
+        if recursive {
+            tokio::fs::remove_dir_all(&resolved).await
+        } else {
+            tokio::fs::remove_dir(&resolved).await
+        }
+    } else {
+        tokio::fs::remove_file(&resolved).await
+    };
+    match result {
+        Ok(()) => ServerResponse::Ok,
+        Err(e) => ServerResponse::Error {
+            message: format!("Failed to remove '{}': {}", path, e),
+        },
+    }
+}
+
+async fn metadata(path: &str, current_dir: &Path) -> ServerResponse {
+    // `resolve_existing_in_sandbox` canonicalizes away any symlinks along the
+    // way (that's how it confirms the *target* is inside the sandbox), so by
+    // the time we have `resolved` it can never itself be a symlink. To report
+    // the link rather than its target, lstat the literal (unresolved) path
+    // the client asked about instead.
+    let resolved = match resolve_existing_in_sandbox(path, current_dir) {
+        Ok(p) => p,
+        Err(message) => return ServerResponse::Error { message },
+    };
+    let literal = to_absolute(path, current_dir);
+
+    if let Ok(link_meta) = tokio::fs::symlink_metadata(&literal).await {
+        if link_meta.is_symlink() {
+            return ServerResponse::Metadata {
+                path: resolved.display().to_string(),
+                file_type: "symlink",
+                len: link_meta.len(),
+                readonly: link_meta.permissions().readonly(),
+                modified: link_meta.modified().ok().and_then(to_unix_secs),
+                accessed: link_meta.accessed().ok().and_then(to_unix_secs),
+            };
+        }
+    }
+
+    match tokio::fs::metadata(&resolved).await {
+        Ok(meta) => ServerResponse::Metadata {
+            path: resolved.display().to_string(),
+            file_type: if meta.is_dir() { "dir" } else { "file" },
+            len: meta.len(),
+            readonly: meta.permissions().readonly(),
+            modified: meta.modified().ok().and_then(to_unix_secs),
+            accessed: meta.accessed().ok().and_then(to_unix_secs),
+        },
+        Err(e) => ServerResponse::Error {
+            message: format!("Failed to stat '{}': {}", path, e),
+        },
+    }
+}
+
+fn to_unix_secs(t: std::time::SystemTime) -> Option<u64> {
+    t.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
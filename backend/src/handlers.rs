@@ -1,22 +1,45 @@
 use axum::{
-    extract::{State, connect_info::ConnectInfo, ws::WebSocketUpgrade},
-    response::Response,
+    body::Body,
+    extract::{
+        Multipart, Query, State, connect_info::ConnectInfo, ws::WebSocketUpgrade, Path as UrlPath,
+    },
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
 };
-use std::{net::SocketAddr, sync::Arc};
+use std::{collections::HashMap, net::SocketAddr, path::Path, sync::Arc};
+use tokio_util::io::ReaderStream;
 use tracing;
 
 use crate::state::AppState;
 
-use crate::ws_logic::handle_socket;
+use crate::ws_logic::{
+    ConnectionMode, SANDBOX_ROOT, handle_socket, resolve_existing_in_sandbox, resolve_new_in_sandbox,
+};
 
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
-    State(_state): State<Arc<AppState>>,
+    State(state): State<Arc<AppState>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(params): Query<HashMap<String, String>>,
 ) -> Response {
     let peer = addr.to_string();
-    tracing::info!("New WebSocket connection from: {:?}", peer);
-    ws.on_upgrade(move |socket| handle_socket(socket, peer))
+    // `?mode=pty` negotiates a raw PTY-backed session instead of the default
+    // line-command mode, so existing clients keep working unchanged.
+    let mode = match params.get("mode").map(String::as_str) {
+        Some("pty") => ConnectionMode::Pty,
+        _ => ConnectionMode::Line,
+    };
+    // `?session=<id>` asks a pty-mode connection to reattach to a
+    // previously opened session's still-running shell instead of starting
+    // a fresh one; unknown or absent ids just open a new session.
+    let session_param = params.get("session").cloned();
+    tracing::info!(
+        "New WebSocket connection from: {:?} (mode: {:?}, session: {:?})",
+        peer,
+        mode,
+        session_param
+    );
+    ws.on_upgrade(move |socket| handle_socket(socket, peer, mode, state, session_param))
 }
 
 pub async fn hello_world() -> &'static str {
@@ -27,3 +50,95 @@ pub async fn handle_404() -> impl axum::response::IntoResponse {
     tracing::warn!("404 Not Found");
     (axum::http::StatusCode::NOT_FOUND, "Not Found")
 }
+
+/// `GET /api/files/*path` — streams a file out of the sandbox with its
+/// `Content-Type` guessed from the extension, instead of loading it fully
+/// into memory first.
+pub async fn download_file(UrlPath(path): UrlPath<String>) -> Response {
+    let resolved = match resolve_existing_in_sandbox(&path, Path::new(SANDBOX_ROOT)) {
+        Ok(p) => p,
+        Err(message) => return (StatusCode::FORBIDDEN, message).into_response(),
+    };
+
+    if !resolved.is_file() {
+        return (StatusCode::NOT_FOUND, "Not a file").into_response();
+    }
+
+    let file = match tokio::fs::File::open(&resolved).await {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::error!("Failed to open '{}' for download: {:?}", resolved.display(), e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to open file").into_response();
+        }
+    };
+
+    let mime = mime_guess::from_path(&resolved).first_or_octet_stream();
+    let body = Body::from_stream(ReaderStream::new(file));
+
+    (
+        [(header::CONTENT_TYPE, mime.as_ref().to_string())],
+        body,
+    )
+        .into_response()
+}
+
+/// `POST /api/files/*path` — expects a `multipart/form-data` body (what a
+/// plain `<input type="file">` + `FormData` upload produces) with the file
+/// in its first field, and writes just that field's bytes to a
+/// sandbox-validated path. `..` traversal, absolute escapes, and symlink
+/// escapes are all rejected with `403`, same as every other entry point
+/// into the sandbox.
+pub async fn upload_file(UrlPath(path): UrlPath<String>, mut multipart: Multipart) -> Response {
+    let resolved = match resolve_new_in_sandbox(&path, Path::new(SANDBOX_ROOT)) {
+        Ok(p) => p,
+        Err(message) => return (StatusCode::FORBIDDEN, message).into_response(),
+    };
+
+    let field = match multipart.next_field().await {
+        Ok(Some(field)) => field,
+        Ok(None) => return (StatusCode::BAD_REQUEST, "Missing upload field").into_response(),
+        Err(e) => {
+            tracing::warn!("Malformed multipart upload to '{}': {:?}", path, e);
+            return (StatusCode::BAD_REQUEST, "Malformed multipart body").into_response();
+        }
+    };
+    let data = match field.bytes().await {
+        Ok(data) => data,
+        Err(e) => {
+            tracing::warn!("Failed to read upload field for '{}': {:?}", path, e);
+            return (StatusCode::BAD_REQUEST, "Failed to read upload field").into_response();
+        }
+    };
+
+    if let Some(parent) = resolved.parent() {
+        if let Err(e) = tokio::fs::create_dir_all(parent).await {
+            tracing::error!("Failed to prepare upload directory '{}': {:?}", parent.display(), e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to prepare upload path").into_response();
+        }
+    }
+
+    match tokio::fs::write(&resolved, &data).await {
+        Ok(()) => StatusCode::CREATED.into_response(),
+        Err(e) => {
+            tracing::error!("Failed to write upload to '{}': {:?}", resolved.display(), e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to write file").into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_route_path_resolution_rejects_dotdot_escape() {
+        let result = resolve_new_in_sandbox("../../../../../../etc/passwd", Path::new(SANDBOX_ROOT));
+        assert!(result.is_err(), "expected '..' escape to be rejected, got {:?}", result);
+    }
+
+    #[test]
+    fn upload_route_path_resolution_rejects_absolute_escape() {
+        let result = resolve_new_in_sandbox("/etc/passwd", Path::new(SANDBOX_ROOT));
+        assert!(result.is_err(), "expected absolute escape to be rejected, got {:?}", result);
+    }
+}
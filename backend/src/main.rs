@@ -1,5 +1,11 @@
+mod fs;
 mod handlers;
+mod protocol;
+mod pty;
+mod search;
+mod session;
 mod state;
+mod watcher;
 mod ws_logic;
 
 use axum::{
@@ -16,7 +22,7 @@ use tower_http::trace::{self, TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 // use colored::*;
-use crate::handlers::{handle_404, hello_world, websocket_handler};
+use crate::handlers::{download_file, handle_404, hello_world, upload_file, websocket_handler};
 
 #[tokio::main]
 async fn main() {
@@ -33,6 +39,22 @@ async fn main() {
 
     let app_state = std::sync::Arc::new(state::AppState::default());
 
+    // --- 后台任务：定期清理空闲过久的会话（关闭会话时会杀掉并回收它持有的
+    //     PTY shell，同时释放 cwd/scrollback） ---
+    let idle_timeout = std::env::var("SESSION_IDLE_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(std::time::Duration::from_secs)
+        .unwrap_or(std::time::Duration::from_secs(30 * 60));
+    let reaper_state = app_state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            reaper_state.sessions.reap_idle(idle_timeout).await;
+        }
+    });
+
     // --- 配置静态文件服务 ---
     let static_files_path = std::env::current_dir().unwrap().join("../frontend/dist");
     tracing::debug!("Serving static files from: {:?}", static_files_path);
@@ -44,6 +66,7 @@ async fn main() {
             ServeDir::new(static_files_path).not_found_service(get(handle_404)),
         ))
         .route("/api/hello", get(hello_world))
+        .route("/api/files/*path", get(download_file).post(upload_file))
         .with_state(app_state)
         .layer(
             TraceLayer::new_for_http()
@@ -0,0 +1,83 @@
+// backend/src/protocol.rs
+//! Protocol version and capability negotiation. The server advertises what
+//! it supports right after the WebSocket upgrade; the client echoes back
+//! the version/features it wants before any command or request is
+//! processed. This keeps the frontend forward- and backward-compatible as
+//! features get added here.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Bumped whenever the wire format changes in a way older clients can't
+/// parse. Connections that ask for a different version are rejected.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Pty,
+    Fs,
+    Watch,
+    Search,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 4] = [
+        Capability::Pty,
+        Capability::Fs,
+        Capability::Watch,
+        Capability::Search,
+    ];
+}
+
+/// Sent by the server immediately after the WebSocket upgrade completes.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum ServerHello {
+    Hello {
+        protocol_version: u32,
+        capabilities: Vec<Capability>,
+    },
+}
+
+/// The client's reply to `ServerHello`. An empty `capabilities` list means
+/// "give me everything the server supports".
+#[derive(Debug, Deserialize)]
+pub struct ClientHello {
+    pub protocol_version: u32,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+}
+
+/// Sent instead of `ServerHello`'s handshake succeeding when the client
+/// asked for a protocol version the server can't speak; the connection is
+/// closed right after.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum HandshakeError {
+    HandshakeError { message: String },
+}
+
+/// The capability set actually enabled for one connection, after
+/// negotiation. May be a subset of what the server supports if the client
+/// asked for less.
+#[derive(Debug, Clone, Default)]
+pub struct NegotiatedCapabilities(HashSet<Capability>);
+
+impl NegotiatedCapabilities {
+    pub fn all() -> Self {
+        Self(Capability::ALL.into_iter().collect())
+    }
+
+    pub fn from_requested(requested: &[Capability]) -> Self {
+        if requested.is_empty() {
+            Self::all()
+        } else {
+            Self(requested.iter().copied().collect())
+        }
+    }
+
+    pub fn has(&self, capability: Capability) -> bool {
+        self.0.contains(&capability)
+    }
+}
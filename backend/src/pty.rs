@@ -0,0 +1,81 @@
+// backend/src/pty.rs
+//! PTY-backed interactive shell sessions.
+//!
+//! Unlike `ws_logic::process_command`, which spawns a fresh child process
+//! per line and only understands a fixed command whitelist, a `PtySession`
+//! spawns one long-lived login shell behind a pseudoterminal so interactive
+//! programs (editors, pagers, REPLs, tab-completion, Ctrl-C) behave the way
+//! they would in a real terminal.
+
+use std::io;
+use std::path::Path;
+
+use portable_pty::{Child, CommandBuilder, MasterPty, PtySize, native_pty_system};
+
+/// A spawned login shell attached to its own pseudoterminal.
+pub struct PtySession {
+    master: Box<dyn MasterPty + Send>,
+    child: Box<dyn Child + Send + Sync>,
+}
+
+impl PtySession {
+    /// Spawn `$SHELL -l` (falling back to `/bin/sh`) with its working
+    /// directory pinned to `cwd`.
+    pub fn spawn(cwd: &Path) -> io::Result<Self> {
+        let pty_system = native_pty_system();
+        let pair = pty_system
+            .openpty(PtySize {
+                rows: 24,
+                cols: 80,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .map_err(to_io_err)?;
+
+        let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+        let mut cmd = CommandBuilder::new(shell);
+        cmd.arg("-l");
+        cmd.cwd(cwd);
+
+        let child = pair.slave.spawn_command(cmd).map_err(to_io_err)?;
+        // Only needed to spawn the child; dropping our copy lets the reader
+        // see EOF once the shell itself exits instead of hanging forever.
+        drop(pair.slave);
+
+        Ok(Self {
+            master: pair.master,
+            child,
+        })
+    }
+
+    /// Propagate an xterm.js window resize via `TIOCSWINSZ`.
+    pub fn resize(&self, cols: u16, rows: u16) -> io::Result<()> {
+        self.master
+            .resize(PtySize {
+                rows,
+                cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .map_err(to_io_err)
+    }
+
+    pub fn try_clone_reader(&self) -> io::Result<Box<dyn io::Read + Send>> {
+        self.master.try_clone_reader().map_err(to_io_err)
+    }
+
+    pub fn take_writer(&self) -> io::Result<Box<dyn io::Write + Send>> {
+        self.master.take_writer().map_err(to_io_err)
+    }
+
+    /// Kill and reap the child so it doesn't linger as a zombie once the
+    /// WebSocket goes away.
+    pub fn kill_and_reap(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn to_io_err(e: anyhow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
@@ -0,0 +1,316 @@
+// backend/src/search.rs
+//! Recursive path/content search confined to the sandbox, available as both
+//! the `search` shell command and a structured `Search` request. Matches are
+//! streamed to the client as they're found rather than buffered, since a
+//! deep tree can produce a lot of them.
+
+use axum::extract::ws::{Message, WebSocket};
+use futures_util::SinkExt;
+use futures_util::stream::SplitSink;
+use regex::RegexBuilder;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::ws_logic::{SANDBOX_ROOT, WebSocketResponse};
+
+/// Options controlling a single search run. `content` switches between
+/// matching file/directory paths (relative to the search root) and matching
+/// file contents line-by-line.
+#[derive(Debug, Default)]
+pub struct SearchOptions {
+    pub content: bool,
+    pub case_insensitive: bool,
+    pub max_depth: Option<usize>,
+    pub glob: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// True if `path` is safe to read or recurse into during a walk: never a
+/// symlink (even one that resolves back inside the sandbox is refused,
+/// since a writable directory elsewhere in the tree could otherwise be used
+/// to hop out via an attacker-controlled link), and canonicalizes to
+/// somewhere under `SANDBOX_ROOT`.
+fn is_within_sandbox(path: &Path, file_type: &std::fs::FileType) -> bool {
+    if file_type.is_symlink() {
+        return false;
+    }
+    path.canonicalize()
+        .map(|c| c.starts_with(SANDBOX_ROOT))
+        .unwrap_or(false)
+}
+
+/// Whether a path (relative to the search root) counts as a match. `content_mode` means the
+/// client asked to grep file bodies, not names, so path-name matching is
+/// skipped entirely in that mode instead of running additively alongside
+/// content matching.
+fn name_matches(
+    relative_name: &str,
+    regex: &regex::Regex,
+    glob_matches: bool,
+    content_mode: bool,
+) -> bool {
+    !content_mode && glob_matches && regex.is_match(relative_name)
+}
+
+/// Walk `root` looking for `pattern`, sending one `WebSocketResponse` chunk
+/// per match directly to `sender`. Returns the number of matches sent.
+pub async fn run(
+    sender: &mut SplitSink<WebSocket, Message>,
+    root: &Path,
+    pattern: &str,
+    opts: &SearchOptions,
+) -> Result<usize, String> {
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(opts.case_insensitive)
+        .build()
+        .map_err(|e| format!("Invalid search pattern '{}': {}", pattern, e))?;
+
+    let glob_pattern = match &opts.glob {
+        Some(g) => Some(
+            glob::Pattern::new(g).map_err(|e| format!("Invalid glob '{}': {}", g, e))?,
+        ),
+        None => None,
+    };
+
+    let mut found = 0usize;
+    let mut stack: Vec<(PathBuf, usize)> = vec![(root.to_path_buf(), 0)];
+
+    'walk: while let Some((dir, depth)) = stack.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if !is_within_sandbox(&path, &file_type) {
+                continue;
+            }
+
+            let relative_name = path.strip_prefix(root).unwrap_or(&path).to_string_lossy();
+            let glob_matches = glob_pattern
+                .as_ref()
+                .map(|g| g.matches(&relative_name))
+                .unwrap_or(true);
+
+            if name_matches(&relative_name, &regex, glob_matches, opts.content) {
+                if !send_match(sender, &path.display().to_string()).await? {
+                    break 'walk;
+                }
+                found += 1;
+                if let Some(limit) = opts.limit {
+                    if found >= limit {
+                        break 'walk;
+                    }
+                }
+            }
+
+            if file_type.is_dir() {
+                let next_depth = depth + 1;
+                if opts.max_depth.map(|max| next_depth <= max).unwrap_or(true) {
+                    stack.push((path, next_depth));
+                }
+                continue;
+            }
+
+            if opts.content && glob_matches {
+                let sent = search_file_content(sender, &path, &regex, opts.limit, &mut found).await?;
+                if !sent {
+                    break 'walk;
+                }
+                if let Some(limit) = opts.limit {
+                    if found >= limit {
+                        break 'walk;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Reads `path` line by line, sending a chunk for each regex match. Binary
+/// files (detected by a NUL byte in the first few KB) are skipped. Returns
+/// `false` if the socket closed mid-stream, signalling the caller to stop.
+async fn search_file_content(
+    sender: &mut SplitSink<WebSocket, Message>,
+    path: &Path,
+    regex: &regex::Regex,
+    limit: Option<usize>,
+    found: &mut usize,
+) -> Result<bool, String> {
+    let Ok(file) = std::fs::File::open(path) else {
+        return Ok(true);
+    };
+    let mut probe = [0u8; 4096];
+    let mut probe_reader = std::io::BufReader::new(&file);
+    let probe_len = probe_reader.read(&mut probe).unwrap_or(0);
+    if probe[..probe_len].contains(&0) {
+        return Ok(true); // 跳过疑似二进制文件
+    }
+
+    let reader = BufReader::new(std::fs::File::open(path).map_err(|e| e.to_string())?);
+    for (idx, line) in reader.lines().enumerate() {
+        let Ok(line) = line else {
+            break; // 非 UTF-8 内容，当作二进制跳过剩余部分
+        };
+        if regex.is_match(&line) {
+            let formatted = format!("{}:{}: {}", path.display(), idx + 1, line);
+            if !send_match(sender, &formatted).await? {
+                return Ok(false);
+            }
+            *found += 1;
+            if let Some(limit) = limit {
+                if *found >= limit {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Parses the `search` shell command's arguments, e.g.
+/// `search --content --ignore-case --glob *.rs TODO`.
+pub fn parse_args(args: &[&str]) -> Result<(String, SearchOptions), String> {
+    let mut opts = SearchOptions::default();
+    let mut pattern: Option<String> = None;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i] {
+            "--content" => opts.content = true,
+            "--ignore-case" | "-i" => opts.case_insensitive = true,
+            "--max-depth" => {
+                i += 1;
+                let value = args.get(i).ok_or("--max-depth requires a value")?;
+                opts.max_depth = Some(
+                    value
+                        .parse()
+                        .map_err(|_| "--max-depth expects a number".to_string())?,
+                );
+            }
+            "--glob" => {
+                i += 1;
+                let value = args.get(i).ok_or("--glob requires a value")?;
+                opts.glob = Some(value.to_string());
+            }
+            "--limit" => {
+                i += 1;
+                let value = args.get(i).ok_or("--limit requires a value")?;
+                opts.limit = Some(
+                    value
+                        .parse()
+                        .map_err(|_| "--limit expects a number".to_string())?,
+                );
+            }
+            other if pattern.is_none() => pattern = Some(other.to_string()),
+            other => return Err(format!("Unexpected argument: {}", other)),
+        }
+        i += 1;
+    }
+    let pattern = pattern
+        .ok_or_else(|| "search requires a pattern, e.g. `search --content TODO`".to_string())?;
+    Ok((pattern, opts))
+}
+
+async fn send_match(sender: &mut SplitSink<WebSocket, Message>, line: &str) -> Result<bool, String> {
+    let response = WebSocketResponse {
+        output: Some(format!("{}\r\n", line)),
+        cwd_update: None,
+    };
+    match sender
+        .send(Message::Text(serde_json::to_string(&response).unwrap().into()))
+        .await
+    {
+        Ok(()) => Ok(true),
+        Err(_) => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_args_reads_flags_and_pattern() {
+        let (pattern, opts) = parse_args(&[
+            "--content",
+            "--ignore-case",
+            "--glob",
+            "*.rs",
+            "--limit",
+            "5",
+            "TODO",
+        ])
+        .unwrap();
+        assert_eq!(pattern, "TODO");
+        assert!(opts.content);
+        assert!(opts.case_insensitive);
+        assert_eq!(opts.glob.as_deref(), Some("*.rs"));
+        assert_eq!(opts.limit, Some(5));
+    }
+
+    #[test]
+    fn parse_args_requires_a_pattern() {
+        assert!(parse_args(&["--content"]).is_err());
+    }
+
+    #[test]
+    fn parse_args_rejects_unexpected_extra_argument() {
+        assert!(parse_args(&["TODO", "extra"]).is_err());
+    }
+
+    #[test]
+    fn is_within_sandbox_skips_symlinks_even_when_target_is_inside() {
+        let dir = PathBuf::from(SANDBOX_ROOT).join("test-fixture-search-symlink");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("failed to create sandbox fixture dir");
+        let real = dir.join("real.txt");
+        std::fs::write(&real, b"x").expect("failed to create real file");
+        let link = dir.join("link.txt");
+        std::os::unix::fs::symlink(&real, &link).expect("failed to create test symlink");
+
+        let file_type = std::fs::symlink_metadata(&link)
+            .expect("failed to lstat test symlink")
+            .file_type();
+        assert!(!is_within_sandbox(&link, &file_type));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn name_matches_checks_path_name_when_not_in_content_mode() {
+        let regex = regex::Regex::new("TODO").unwrap();
+        assert!(name_matches("TODO.txt", &regex, true, false));
+        assert!(!name_matches("readme.txt", &regex, true, false));
+    }
+
+    #[test]
+    fn name_matches_is_always_false_in_content_mode() {
+        let regex = regex::Regex::new("TODO").unwrap();
+        // Even though the name matches the pattern, content mode means
+        // "match file bodies," not "match bodies in addition to names."
+        assert!(!name_matches("TODO.txt", &regex, true, true));
+    }
+
+    #[test]
+    fn is_within_sandbox_allows_plain_files_inside() {
+        let dir = PathBuf::from(SANDBOX_ROOT).join("test-fixture-search-plain");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("failed to create sandbox fixture dir");
+        let file = dir.join("plain.txt");
+        std::fs::write(&file, b"x").expect("failed to create plain file");
+
+        let file_type = std::fs::symlink_metadata(&file)
+            .expect("failed to lstat test file")
+            .file_type();
+        assert!(is_within_sandbox(&file, &file_type));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
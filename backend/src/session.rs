@@ -0,0 +1,213 @@
+// backend/src/session.rs
+//! Named, resumable terminal sessions. A bare WebSocket connection's
+//! `current_dir`/scrollback (line mode) or shell process (pty mode) die the
+//! moment the socket closes; a `Session` lives in `AppState` independent of
+//! any one connection, so a reconnecting client can `AttachSession` (line
+//! mode) or reconnect with `?mode=pty&session=<id>` (pty mode) to pick up
+//! where it left off, and a single browser can drive several concurrent
+//! terminals by opening more than one.
+//!
+//! A pty-mode session's shell survives its connection closing: the first
+//! connection to use a session spawns the `PtySession` and a single
+//! background thread that reads its output and fans it out over a broadcast
+//! channel, so every (re)connection just subscribes instead of spawning its
+//! own reader. The shell itself is only killed by an explicit `CloseSession`
+//! or by `reap_idle`, never by a connection merely dropping.
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::pty::PtySession;
+use crate::ws_logic::SANDBOX_ROOT;
+
+pub type SessionId = String;
+
+/// How many scrollback lines to retain for replay on attach (line mode).
+const SCROLLBACK_LIMIT: usize = 2000;
+
+/// How many not-yet-delivered output chunks a lagging pty subscriber can
+/// fall behind by before older ones are dropped for it.
+const PTY_OUTPUT_BUFFER: usize = 256;
+
+/// A session's live shell: the pty itself, plus the broadcast channel its
+/// background reader thread publishes output chunks to.
+pub struct PtyHandle {
+    pub pty: PtySession,
+    output: broadcast::Sender<Vec<u8>>,
+}
+
+pub struct Session {
+    pub cwd: PathBuf,
+    pub scrollback: VecDeque<String>,
+    /// Set on the first pty-mode connection to use this session; kept alive
+    /// across disconnects so the shell survives until the session is
+    /// explicitly closed or reaped for being idle.
+    pub pty: Option<PtyHandle>,
+    pub last_active: Instant,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            cwd: PathBuf::from(SANDBOX_ROOT),
+            scrollback: VecDeque::with_capacity(SCROLLBACK_LIMIT),
+            pty: None,
+            last_active: Instant::now(),
+        }
+    }
+
+    pub fn push_scrollback(&mut self, chunk: &str) {
+        for line in chunk.lines() {
+            if self.scrollback.len() >= SCROLLBACK_LIMIT {
+                self.scrollback.pop_front();
+            }
+            self.scrollback.push_back(line.to_string());
+        }
+        self.last_active = Instant::now();
+    }
+
+    pub fn replay(&self) -> String {
+        self.scrollback
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\r\n")
+    }
+
+    pub fn touch(&mut self) {
+        self.last_active = Instant::now();
+    }
+
+    /// Makes sure this session owns a live shell, spawning one (and its
+    /// background reader thread) if it doesn't yet. Returns a fresh
+    /// subscriber to the shell's output — note this only streams output
+    /// from here on; pty bytes aren't kept in `scrollback`, so a reattach
+    /// doesn't replay history the way line mode does.
+    pub fn ensure_pty(&mut self, cwd: &Path) -> io::Result<broadcast::Receiver<Vec<u8>>> {
+        if self.pty.is_none() {
+            let pty = PtySession::spawn(cwd)?;
+            let mut reader = pty.try_clone_reader()?;
+            let (tx, _rx) = broadcast::channel(PTY_OUTPUT_BUFFER);
+            let reader_tx = tx.clone();
+            tokio::task::spawn_blocking(move || {
+                let mut buf = [0u8; 4096];
+                loop {
+                    match reader.read(&mut buf) {
+                        Ok(0) => break,
+                        Ok(n) => {
+                            // Err means no subscribers are currently
+                            // attached; that's fine, keep draining the pty
+                            // so the shell doesn't block on a full buffer.
+                            let _ = reader_tx.send(buf[..n].to_vec());
+                        }
+                        Err(_) => break,
+                    }
+                }
+                // Dropping `reader_tx` here doesn't close the channel by
+                // itself (the `PtyHandle` still holds the original `tx`),
+                // but it does mean no more output will ever be sent, and
+                // the child is presumed dead/dying at this point.
+            });
+            self.pty = Some(PtyHandle { pty, output: tx });
+        }
+        Ok(self.pty.as_ref().unwrap().output.subscribe())
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SessionSummary {
+    pub id: SessionId,
+    pub cwd: String,
+    pub idle_secs: u64,
+}
+
+/// All sessions live here, independent of any one connection.
+#[derive(Default)]
+pub struct SessionManager {
+    sessions: DashMap<SessionId, Arc<Mutex<Session>>>,
+}
+
+impl SessionManager {
+    pub fn open(&self) -> SessionId {
+        let id = uuid::Uuid::new_v4().to_string();
+        self.sessions.insert(id.clone(), Arc::new(Mutex::new(Session::new())));
+        id
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Mutex<Session>>> {
+        self.sessions.get(id).map(|entry| entry.value().clone())
+    }
+
+    /// Removes the session and kills its shell, if it has one. Awaits the
+    /// session's own lock rather than `try_lock`-ing it, so a session that's
+    /// mid-update (e.g. a concurrent command updating `cwd`/scrollback)
+    /// still gets its child killed instead of silently skipping cleanup.
+    pub async fn close(&self, id: &str) -> bool {
+        match self.sessions.remove(id) {
+            Some((_, session)) => {
+                let mut session = session.lock().await;
+                if let Some(handle) = session.pty.as_mut() {
+                    handle.pty.kill_and_reap();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub async fn list(&self) -> Vec<SessionSummary> {
+        // Collect key/Arc pairs first and drop the dashmap shard iterator
+        // before locking any session: holding a shard guard across an
+        // `.await` on an unrelated per-session lock is a contention/
+        // deadlock risk under load (a concurrent insert/remove on the same
+        // shard would block behind it).
+        let entries: Vec<(SessionId, Arc<Mutex<Session>>)> = self
+            .sessions
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+
+        let mut summaries = Vec::with_capacity(entries.len());
+        for (id, session) in entries {
+            let session = session.lock().await;
+            summaries.push(SessionSummary {
+                id,
+                cwd: session.cwd.display().to_string(),
+                idle_secs: session.last_active.elapsed().as_secs(),
+            });
+        }
+        summaries
+    }
+
+    /// Closes and kills every session idle longer than `timeout`. Intended
+    /// to run on a timer from a background task.
+    pub async fn reap_idle(&self, timeout: Duration) {
+        // Same dashmap-guard-across-await concern as `list`: collect first,
+        // then lock each session in a separate pass.
+        let entries: Vec<(SessionId, Arc<Mutex<Session>>)> = self
+            .sessions
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+
+        let mut expired = Vec::new();
+        for (id, session) in entries {
+            let session = session.lock().await;
+            if session.last_active.elapsed() > timeout {
+                expired.push(id);
+            }
+        }
+        for id in expired {
+            tracing::info!("Reaping idle session {}", id);
+            self.close(&id).await;
+        }
+    }
+}
@@ -0,0 +1,23 @@
+// backend/src/state.rs
+//! Shared application state handed to every axum handler via `State`.
+
+use dashmap::DashMap;
+
+use crate::protocol::NegotiatedCapabilities;
+use crate::session::SessionManager;
+use crate::watcher::ConnectionWatches;
+
+/// Process-wide state shared across all HTTP/WebSocket connections.
+#[derive(Default)]
+pub struct AppState {
+    /// Active filesystem watch subscriptions, keyed by connection peer
+    /// address. Dropping an entry tears down every watcher it owns.
+    pub watches: DashMap<String, ConnectionWatches>,
+    /// The capability set each connection negotiated at handshake time,
+    /// keyed by peer address. Consulted before dispatching any request for
+    /// a gated feature (pty/fs/watch/search).
+    pub capabilities: DashMap<String, NegotiatedCapabilities>,
+    /// Named, resumable terminal sessions, independent of any one
+    /// connection.
+    pub sessions: SessionManager,
+}
@@ -0,0 +1,91 @@
+// backend/src/watcher.rs
+//! Live filesystem-change subscriptions. A connected client can `Watch` a
+//! sandbox-relative path and receive unsolicited `ServerResponse::Change`
+//! messages as files under it are created, modified, removed or renamed.
+//!
+//! One `ConnectionWatches` lives per WebSocket connection (keyed by peer in
+//! `AppState::watches`) and holds every `notify` watcher that connection has
+//! registered, so dropping the entry on disconnect tears all of them down.
+
+use dashmap::DashMap;
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::fs::ServerResponse;
+
+/// How long to suppress repeated events for the same path, so e.g. an
+/// editor's write-then-rename dance doesn't spam the client with duplicates.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// All active watch subscriptions for one connection.
+#[derive(Default)]
+pub struct ConnectionWatches {
+    subscriptions: DashMap<PathBuf, RecommendedWatcher>,
+}
+
+impl ConnectionWatches {
+    pub fn watch(
+        &self,
+        path: PathBuf,
+        recursive: bool,
+        out: UnboundedSender<ServerResponse>,
+    ) -> notify::Result<()> {
+        let mode = if recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        let last_sent: Arc<Mutex<HashMap<PathBuf, Instant>>> = Arc::new(Mutex::new(HashMap::new()));
+
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+            let Ok(event) = res else {
+                return;
+            };
+            let Some(kind) = classify(event.kind) else {
+                return;
+            };
+            let now = Instant::now();
+            let mut last_sent = last_sent.lock().unwrap();
+            // An entry older than `DEBOUNCE` can no longer suppress anything
+            // (the check below would let it through anyway), so drop it here
+            // instead of leaving it in the map forever. Without this, every
+            // distinct path ever touched under a long-lived recursive watch
+            // accumulates for the life of the subscription.
+            last_sent.retain(|_, last| now.duration_since(*last) < DEBOUNCE);
+            for changed_path in event.paths {
+                if let Some(last) = last_sent.get(&changed_path) {
+                    if now.duration_since(*last) < DEBOUNCE {
+                        continue;
+                    }
+                }
+                last_sent.insert(changed_path.clone(), now);
+                let _ = out.send(ServerResponse::Change {
+                    path: changed_path.display().to_string(),
+                    kind,
+                });
+            }
+        })?;
+        watcher.watch(&path, mode)?;
+        self.subscriptions.insert(path, watcher);
+        Ok(())
+    }
+
+    pub fn unwatch(&self, path: &Path) {
+        self.subscriptions.remove(path);
+    }
+}
+
+fn classify(kind: EventKind) -> Option<&'static str> {
+    match kind {
+        EventKind::Create(_) => Some("create"),
+        EventKind::Remove(_) => Some("remove"),
+        EventKind::Modify(ModifyKind::Name(_)) => Some("rename"),
+        EventKind::Modify(_) => Some("modify"),
+        _ => None,
+    }
+}
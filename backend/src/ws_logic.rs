@@ -4,10 +4,35 @@ use axum::extract::ws::WebSocket;
 use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use shlex; // 用于解析命令字符串，处理引号和空格
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::process::Command; // 用于执行系统命令
+use tokio::sync::broadcast;
 use tracing; // 日志库
 
+use crate::fs;
+use crate::protocol;
+use crate::search;
+use crate::session;
+use crate::state::AppState;
+
+/// 连接协商的会话模式：`Line` 是原来逐行解析命令的模式，`Pty` 是本次新增的
+/// 原始 PTY 透传模式，两者互斥，在 WebSocket 升级时由 query string 决定。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionMode {
+    Line,
+    Pty,
+}
+
+/// PTY 模式下，客户端通过 `Message::Text` 发送的控制帧（原始按键走
+/// `Message::Binary`，不会进入这个枚举）。
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PtyControl {
+    Resize { cols: u16, rows: u16 },
+}
+
 // **修复 1: 统一 WebSocket 响应格式，与前端期望的 JSON 对象一致**
 // 这个结构体现在与前端的 `WebSocketMessage` (在 webSocketService.ts 中) 完美匹配。
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -16,7 +41,97 @@ pub struct WebSocketResponse {
     pub cwd_update: Option<String>,
 }
 
-const SANDBOX_ROOT: &str = "/home/heyeuuu/Workspace/secretes/happy_birthday";
+pub(crate) const SANDBOX_ROOT: &str = "/home/heyeuuu/Workspace/secretes/happy_birthday";
+
+/// Resolve a (possibly relative) client-supplied path to an existing,
+/// canonicalized path inside the sandbox. Shared by `cd` and the `fs`
+/// module so every entry point enforces the same confinement rule.
+pub(crate) fn resolve_existing_in_sandbox(
+    path_str: &str,
+    current_dir: &Path,
+) -> Result<PathBuf, String> {
+    let target = to_absolute(path_str, current_dir);
+    match target.canonicalize() {
+        Ok(canonical) if canonical.starts_with(SANDBOX_ROOT) => Ok(canonical),
+        Ok(_) => Err(format!(
+            "Cannot access '{}' outside the sandbox.",
+            path_str
+        )),
+        Err(_) => Err(format!(
+            "Path '{}' is invalid or does not exist.",
+            path_str
+        )),
+    }
+}
+
+/// Resolve a client-supplied path that may not exist yet (write targets,
+/// mkdir, copy/rename destinations). The path is normalized lexically
+/// (so `..` can't be used before the target exists to escape the
+/// sandbox), and the nearest real ancestor is canonicalized to make sure
+/// a symlink hasn't smuggled it outside `SANDBOX_ROOT` either.
+///
+/// The ancestor walk uses `symlink_metadata` (lstat), not `Path::exists()`
+/// (which follows symlinks). That distinction matters: a *dangling*
+/// symlink inside the sandbox pointing outside of it would report
+/// `exists() == false`, so a walk driven by `exists()` steps past it to its
+/// parent and never canonicalizes the link itself, treating the unresolved
+/// `SANDBOX_ROOT/evil_link` as a safe target even though opening it with
+/// `O_CREAT` follows the link straight out of the sandbox. Stopping at the
+/// first path `lstat` can see at all — symlink or not, dangling or not —
+/// means the walk always canonicalizes the link itself: a dangling symlink
+/// fails to canonicalize and an outside-pointing one fails the
+/// `starts_with` check below, so both are rejected.
+pub(crate) fn resolve_new_in_sandbox(path_str: &str, current_dir: &Path) -> Result<PathBuf, String> {
+    let target = to_absolute(path_str, current_dir);
+    let normalized = lexically_normalize(&target);
+    if !normalized.starts_with(SANDBOX_ROOT) {
+        return Err(format!(
+            "Cannot access '{}' outside the sandbox.",
+            path_str
+        ));
+    }
+
+    let mut ancestor: &Path = &normalized;
+    while std::fs::symlink_metadata(ancestor).is_err() {
+        match ancestor.parent() {
+            Some(parent) => ancestor = parent,
+            None => break,
+        }
+    }
+    match ancestor.canonicalize() {
+        Ok(canonical) if canonical.starts_with(SANDBOX_ROOT) => Ok(normalized),
+        Ok(_) => Err(format!(
+            "Cannot access '{}' outside the sandbox.",
+            path_str
+        )),
+        Err(_) => Err(format!("Path '{}' is invalid.", path_str)),
+    }
+}
+
+pub(crate) fn to_absolute(path_str: &str, current_dir: &Path) -> PathBuf {
+    let target_path = PathBuf::from(path_str);
+    if target_path.is_absolute() {
+        target_path
+    } else {
+        current_dir.join(target_path)
+    }
+}
+
+fn lexically_normalize(path: &Path) -> PathBuf {
+    use std::path::Component;
+
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
 
 // **修复 2: 命令处理函数返回的结果结构体**
 // 内部使用这个结构体来封装命令处理结果，方便统一发送。
@@ -44,7 +159,99 @@ fn clean_output(s: String) -> String {
         .collect()
 }
 
-pub async fn handle_socket(socket: WebSocket, peer: String) {
+pub async fn handle_socket(
+    socket: WebSocket,
+    peer: String,
+    mode: ConnectionMode,
+    state: Arc<AppState>,
+    requested_session: Option<session::SessionId>,
+) {
+    let (mut sender, mut receiver) = socket.split();
+
+    let hello = protocol::ServerHello::Hello {
+        protocol_version: protocol::PROTOCOL_VERSION,
+        capabilities: protocol::Capability::ALL.to_vec(),
+    };
+    if sender
+        .send(Message::Text(serde_json::to_string(&hello).unwrap().into()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let negotiated = match receiver.next().await {
+        Some(Ok(Message::Text(text))) => match serde_json::from_str::<protocol::ClientHello>(&text) {
+            Ok(client_hello) if client_hello.protocol_version == protocol::PROTOCOL_VERSION => {
+                protocol::NegotiatedCapabilities::from_requested(&client_hello.capabilities)
+            }
+            Ok(client_hello) => {
+                reject_handshake(
+                    &mut sender,
+                    format!(
+                        "Unsupported protocol version {}; this server speaks {}.",
+                        client_hello.protocol_version,
+                        protocol::PROTOCOL_VERSION
+                    ),
+                )
+                .await;
+                return;
+            }
+            Err(e) => {
+                reject_handshake(&mut sender, format!("Invalid hello message: {}", e)).await;
+                return;
+            }
+        },
+        _ => {
+            tracing::warn!(
+                "Connection from {} closed before completing the handshake",
+                peer
+            );
+            return;
+        }
+    };
+
+    if mode == ConnectionMode::Pty && !negotiated.has(protocol::Capability::Pty) {
+        reject_handshake(
+            &mut sender,
+            "The 'pty' capability was not negotiated for this connection.".to_string(),
+        )
+        .await;
+        return;
+    }
+
+    state.capabilities.insert(peer.clone(), negotiated);
+
+    let socket = match sender.reunite(receiver) {
+        Ok(socket) => socket,
+        Err(_) => {
+            tracing::error!("Failed to reunite websocket halves for {} after handshake", peer);
+            return;
+        }
+    };
+
+    match mode {
+        ConnectionMode::Line => handle_line_socket(socket, peer.clone(), state.clone()).await,
+        ConnectionMode::Pty => {
+            handle_pty_socket(socket, peer.clone(), state.clone(), requested_session).await
+        }
+    }
+
+    state.capabilities.remove(&peer);
+}
+
+async fn reject_handshake(
+    sender: &mut futures_util::stream::SplitSink<WebSocket, Message>,
+    message: String,
+) {
+    let error = protocol::HandshakeError::HandshakeError { message };
+    let _ = sender
+        .send(Message::Text(serde_json::to_string(&error).unwrap().into()))
+        .await;
+    let _ = sender.send(Message::Close(None)).await;
+}
+
+async fn handle_line_socket(socket: WebSocket, peer: String, state: Arc<AppState>) {
     let (mut sender, mut receiver) = socket.split();
 
     let sandbox_root_path = PathBuf::from(SANDBOX_ROOT);
@@ -82,58 +289,530 @@ pub async fn handle_socket(socket: WebSocket, peer: String) {
         return; // 如果无法发送初始消息，断开连接
     }
 
-    while let Some(msg) = receiver.next().await {
-        match msg {
-            Ok(msg) => match msg {
-                Message::Text(text) => {
-                    tracing::info!("Received command from {}: {}", peer, text);
+    // 未经请求的 watch 变更事件经这个 channel 送回主循环，和正常的客户端
+    // 消息一起用 select! 轮询，这样一个连接可以同时处理命令和文件变更推送。
+    let (change_tx, mut change_rx) = tokio::sync::mpsc::unbounded_channel::<fs::ServerResponse>();
 
-                    let CommandResult { output, new_cwd } =
-                        process_command(&text.trim(), &current_dir).await;
+    // 如果这个连接 attach 到了某个具名会话，cwd 和输出会持续同步回那个
+    // 会话，这样断线重连后可以用 AttachSession 继续之前的状态。
+    let mut attached_session: Option<session::SessionId> = None;
 
-                    // 如果 CWD 有更新，先更新后端状态
-                    if let Some(path) = &new_cwd {
-                        current_dir = path.clone();
+    'outer: loop {
+        tokio::select! {
+            change = change_rx.recv() => {
+                match change {
+                    Some(response) => {
+                        if let Err(e) = sender
+                            .send(Message::Text(serde_json::to_string(&response).unwrap().into()))
+                            .await
+                        {
+                            tracing::error!("Failed to push watch event to {}: {:?}", peer, e);
+                            break 'outer;
+                        }
                     }
+                    None => break 'outer,
+                }
+            }
+            msg = receiver.next() => {
+                let Some(msg) = msg else { break 'outer; };
+                match msg {
+                    Ok(Message::Text(text)) => {
+                        tracing::info!("Received command from {}: {}", peer, text);
+
+                        // 结构化文件系统请求走独立的 ClientRequest/ServerResponse
+                        // 协议；其余文本仍按旧的逐行命令解析，向后兼容。
+                        if let Ok(request) = serde_json::from_str::<fs::ClientRequest>(text.trim()) {
+                            if !has_capability(&state, &peer, required_capability(&request)) {
+                                let error = fs::ServerResponse::Error {
+                                    message: format!(
+                                        "The '{:?}' capability was not negotiated for this connection.",
+                                        required_capability(&request)
+                                    ),
+                                };
+                                let _ = sender
+                                    .send(Message::Text(serde_json::to_string(&error).unwrap().into()))
+                                    .await;
+                                continue;
+                            }
+
+                            // Search streams its matches directly over `sender` instead of
+                            // returning a single buffered response, since a deep tree can
+                            // produce a lot of them.
+                            if let fs::ClientRequest::Search {
+                                pattern,
+                                content,
+                                case_insensitive,
+                                max_depth,
+                                glob,
+                                limit,
+                            } = &request
+                            {
+                                let opts = search::SearchOptions {
+                                    content: *content,
+                                    case_insensitive: *case_insensitive,
+                                    max_depth: *max_depth,
+                                    glob: glob.clone(),
+                                    limit: *limit,
+                                };
+                                if let Err(e) = search::run(&mut sender, &current_dir, pattern, &opts).await {
+                                    let error = fs::ServerResponse::Error { message: e };
+                                    let _ = sender
+                                        .send(Message::Text(serde_json::to_string(&error).unwrap().into()))
+                                        .await;
+                                }
+                                continue;
+                            }
+
+                            // Session requests mutate this connection's own cwd (and, for
+                            // `AttachSession`, need to push a second scrollback-replay
+                            // message), so they're handled inline rather than through
+                            // `fs::handle`.
+                            if let fs::ClientRequest::OpenSession = &request {
+                                let id = state.sessions.open();
+                                attached_session = Some(id.clone());
+                                let response = fs::ServerResponse::SessionOpened { id };
+                                if let Err(e) = sender
+                                    .send(Message::Text(serde_json::to_string(&response).unwrap().into()))
+                                    .await
+                                {
+                                    tracing::error!("Failed to send fs response to {}: {:?}", peer, e);
+                                    break 'outer;
+                                }
+                                continue;
+                            }
+                            if let fs::ClientRequest::AttachSession { id } = &request {
+                                let response = match state.sessions.get(id) {
+                                    Some(session) => {
+                                        let session = session.lock().await;
+                                        current_dir = session.cwd.clone();
+                                        let replay = session.replay();
+                                        drop(session);
+                                        attached_session = Some(id.clone());
+                                        let _ = send_line(&mut sender, &replay).await;
+                                        fs::ServerResponse::SessionAttached {
+                                            id: id.clone(),
+                                            cwd: current_dir.display().to_string(),
+                                        }
+                                    }
+                                    None => fs::ServerResponse::Error {
+                                        message: format!("No such session '{}'.", id),
+                                    },
+                                };
+                                if let Err(e) = sender
+                                    .send(Message::Text(serde_json::to_string(&response).unwrap().into()))
+                                    .await
+                                {
+                                    tracing::error!("Failed to send fs response to {}: {:?}", peer, e);
+                                    break 'outer;
+                                }
+                                continue;
+                            }
+                            if let fs::ClientRequest::ListSessions = &request {
+                                let response = fs::ServerResponse::SessionList {
+                                    sessions: state.sessions.list().await,
+                                };
+                                if let Err(e) = sender
+                                    .send(Message::Text(serde_json::to_string(&response).unwrap().into()))
+                                    .await
+                                {
+                                    tracing::error!("Failed to send fs response to {}: {:?}", peer, e);
+                                    break 'outer;
+                                }
+                                continue;
+                            }
+                            if let fs::ClientRequest::CloseSession { id } = &request {
+                                let closed = state.sessions.close(id).await;
+                                if attached_session.as_deref() == Some(id.as_str()) {
+                                    attached_session = None;
+                                }
+                                let response = if closed {
+                                    fs::ServerResponse::Ok
+                                } else {
+                                    fs::ServerResponse::Error {
+                                        message: format!("No such session '{}'.", id),
+                                    }
+                                };
+                                if let Err(e) = sender
+                                    .send(Message::Text(serde_json::to_string(&response).unwrap().into()))
+                                    .await
+                                {
+                                    tracing::error!("Failed to send fs response to {}: {:?}", peer, e);
+                                    break 'outer;
+                                }
+                                continue;
+                            }
+
+                            let response = match &request {
+                                fs::ClientRequest::Watch { path, recursive } => {
+                                    handle_watch(&state, &peer, path, *recursive, &current_dir, change_tx.clone())
+                                }
+                                fs::ClientRequest::Unwatch { path } => {
+                                    handle_unwatch(&state, &peer, path, &current_dir)
+                                }
+                                _ => fs::handle(&request, &current_dir).await,
+                            };
+                            if let Err(e) = sender
+                                .send(Message::Text(serde_json::to_string(&response).unwrap().into()))
+                                .await
+                            {
+                                tracing::error!("Failed to send fs response to {}: {:?}", peer, e);
+                                break 'outer;
+                            }
+                            continue;
+                        }
+
+                        // `search` 同样走流式输出，在进入逐行命令分发前单独拦截。
+                        if let Some(parts) = shlex::split(text.trim()) {
+                            if parts.first().map(|c| c.eq_ignore_ascii_case("search")).unwrap_or(false) {
+                                if !has_capability(&state, &peer, protocol::Capability::Search) {
+                                    let _ = send_line(
+                                        &mut sender,
+                                        "Error: the 'search' capability was not negotiated for this connection.\r\n",
+                                    )
+                                    .await;
+                                    continue;
+                                }
+                                let arg_refs: Vec<&str> = parts[1..].iter().map(String::as_str).collect();
+                                match search::parse_args(&arg_refs) {
+                                    Ok((pattern, opts)) => {
+                                        if let Err(e) =
+                                            search::run(&mut sender, &current_dir, &pattern, &opts).await
+                                        {
+                                            let _ = send_line(&mut sender, &format!("Error: {}\r\n", e)).await;
+                                        }
+                                    }
+                                    Err(e) => {
+                                        let _ = send_line(&mut sender, &format!("Error: {}\r\n", e)).await;
+                                    }
+                                }
+                                continue;
+                            }
+                        }
+
+                        let CommandResult { output, new_cwd } =
+                            process_command(&text.trim(), &current_dir).await;
+
+                        // 如果 CWD 有更新，先更新后端状态
+                        if let Some(path) = &new_cwd {
+                            current_dir = path.clone();
+                        }
+
+                        if let Some(id) = &attached_session {
+                            if let Some(session) = state.sessions.get(id) {
+                                let mut session = session.lock().await;
+                                session.cwd = current_dir.clone();
+                                if !output.is_empty() {
+                                    session.push_scrollback(&output);
+                                } else {
+                                    session.touch();
+                                }
+                            }
+                        }
+
+                        // **修复 4: 统一发送响应，根据 CommandResult 构建 WebSocketResponse**
+                        let response_to_send = WebSocketResponse {
+                            output: if output.is_empty() {
+                                None
+                            } else {
+                                Some(clean_output(output))
+                            },
+                            cwd_update: new_cwd.map(|p| p.display().to_string()),
+                        };
 
-                    // **修复 4: 统一发送响应，根据 CommandResult 构建 WebSocketResponse**
-                    let response_to_send = WebSocketResponse {
-                        output: if output.is_empty() {
-                            None
-                        } else {
-                            Some(clean_output(output))
-                        },
-                        cwd_update: new_cwd.map(|p| p.display().to_string()),
-                    };
-
-                    if let Err(e) = sender
-                        .send(Message::Text(
-                            serde_json::to_string(&response_to_send).unwrap().into(),
-                        ))
-                        .await
-                    {
-                        tracing::error!("Failed to send response to {}: {:?}", peer, e);
-                        break; // 发送失败则退出循环
+                        if let Err(e) = sender
+                            .send(Message::Text(
+                                serde_json::to_string(&response_to_send).unwrap().into(),
+                            ))
+                            .await
+                        {
+                            tracing::error!("Failed to send response to {}: {:?}", peer, e);
+                            break 'outer; // 发送失败则退出循环
+                        }
+                    }
+                    Ok(Message::Pong(_)) => {
+                        // tracing::info!("Received Pong from {}", peer); // 过于频繁，可以注释掉
+                    }
+                    Ok(Message::Close(c)) => {
+                        tracing::info!("Connection closed by {}: {:?}", peer, c);
+                        break 'outer;
+                    }
+                    Ok(other) => {
+                        tracing::warn!("Unsupported message type from {}: {:?}", peer, other);
+                    }
+                    Err(err) => {
+                        tracing::error!("WebSocket error for `{}`: {}", peer, err);
+                        break 'outer;
                     }
                 }
-                Message::Pong(_) => {
-                    // tracing::info!("Received Pong from {}", peer); // 过于频繁，可以注释掉
+            }
+        }
+    }
+
+    // 连接关闭时，清理这个连接名下的所有 watch 订阅，让对应的 notify
+    // watcher 一并释放。
+    state.watches.remove(&peer);
+    tracing::info!("`{}` WebSocket connection closed.", peer);
+}
+
+fn handle_watch(
+    state: &AppState,
+    peer: &str,
+    path: &str,
+    recursive: bool,
+    current_dir: &Path,
+    out: tokio::sync::mpsc::UnboundedSender<fs::ServerResponse>,
+) -> fs::ServerResponse {
+    let resolved = match resolve_existing_in_sandbox(path, current_dir) {
+        Ok(p) => p,
+        Err(message) => return fs::ServerResponse::Error { message },
+    };
+    let watches = state.watches.entry(peer.to_string()).or_default();
+    match watches.watch(resolved, recursive, out) {
+        Ok(()) => fs::ServerResponse::Ok,
+        Err(e) => fs::ServerResponse::Error {
+            message: format!("Failed to watch '{}': {}", path, e),
+        },
+    }
+}
+
+fn has_capability(state: &AppState, peer: &str, capability: protocol::Capability) -> bool {
+    state
+        .capabilities
+        .get(peer)
+        .map(|c| c.has(capability))
+        .unwrap_or(false)
+}
+
+fn required_capability(request: &fs::ClientRequest) -> protocol::Capability {
+    match request {
+        fs::ClientRequest::Watch { .. } | fs::ClientRequest::Unwatch { .. } => {
+            protocol::Capability::Watch
+        }
+        fs::ClientRequest::Search { .. } => protocol::Capability::Search,
+        // Sessions exist to persist a line-mode connection's cwd/scrollback
+        // (see session.rs), so they're gated behind the same `Fs` capability
+        // as the file-operation variants below rather than getting a
+        // dedicated one — there's no session-only capability to negotiate.
+        fs::ClientRequest::OpenSession
+        | fs::ClientRequest::AttachSession { .. }
+        | fs::ClientRequest::ListSessions
+        | fs::ClientRequest::CloseSession { .. } => protocol::Capability::Fs,
+        fs::ClientRequest::FileRead { .. }
+        | fs::ClientRequest::FileWrite { .. }
+        | fs::ClientRequest::Copy { .. }
+        | fs::ClientRequest::Rename { .. }
+        | fs::ClientRequest::MakeDir { .. }
+        | fs::ClientRequest::Remove { .. }
+        | fs::ClientRequest::Metadata { .. } => protocol::Capability::Fs,
+    }
+}
+
+async fn send_line(
+    sender: &mut futures_util::stream::SplitSink<WebSocket, Message>,
+    line: &str,
+) -> Result<(), axum::Error> {
+    sender
+        .send(Message::Text(
+            serde_json::to_string(&WebSocketResponse {
+                output: Some(clean_output(line.to_string())),
+                cwd_update: None,
+            })
+            .unwrap()
+            .into(),
+        ))
+        .await
+}
+
+fn handle_unwatch(state: &AppState, peer: &str, path: &str, current_dir: &Path) -> fs::ServerResponse {
+    let resolved = match resolve_existing_in_sandbox(path, current_dir) {
+        Ok(p) => p,
+        Err(message) => return fs::ServerResponse::Error { message },
+    };
+    if let Some(watches) = state.watches.get(peer) {
+        watches.unwatch(&resolved);
+    }
+    fs::ServerResponse::Ok
+}
+
+/// PTY 模式下的连接处理：起一个登录 shell（或复用一个已有会话正在运行的
+/// shell），双向转发原始字节。
+///
+/// 读 PTY 只有一个后台阻塞线程，属于 `Session`，在 `Session::ensure_pty`
+/// 里按需起一次；本连接只是通过 `broadcast` channel 订阅它的输出，断线时
+/// 只丢弃订阅者，不杀掉 shell，这样客户端带着同一个 `session` id 重连就能
+/// 接上原来的 shell。写 PTY 仍然各自在独立的 `spawn_blocking` 线程里进行
+/// （`take_writer` 可以重复调用拿到独立的写句柄）。shell 真正被杀掉只发生
+/// 在显式 `CloseSession` 或空闲过久被 `reap_idle` 回收时。
+async fn handle_pty_socket(
+    socket: WebSocket,
+    peer: String,
+    state: Arc<AppState>,
+    requested_session: Option<session::SessionId>,
+) {
+    let (mut sender, mut receiver) = socket.split();
+    let sandbox_root_path = PathBuf::from(SANDBOX_ROOT);
+
+    let (session_id, session_handle, reused) = match requested_session
+        .and_then(|id| state.sessions.get(&id).map(|handle| (id, handle)))
+    {
+        Some((id, handle)) => (id, handle, true),
+        None => {
+            let id = state.sessions.open();
+            let handle = state.sessions.get(&id).expect("just opened this session");
+            (id, handle, false)
+        }
+    };
+
+    let mut output_rx = {
+        let mut guard = session_handle.lock().await;
+        match guard.ensure_pty(&sandbox_root_path) {
+            Ok(rx) => rx,
+            Err(e) => {
+                tracing::error!("Failed to spawn pty for {}: {:?}", peer, e);
+                let _ = sender
+                    .send(Message::Text(
+                        serde_json::to_string(&WebSocketResponse {
+                            output: Some(format!(
+                                "Error: failed to start interactive shell: {}\r\n",
+                                e
+                            )),
+                            cwd_update: None,
+                        })
+                        .unwrap()
+                        .into(),
+                    ))
+                    .await;
+                return;
+            }
+        }
+    };
+
+    // 把分配到的/复用的 session id 告诉客户端，这样它断线重连时可以带上
+    // `?session=<id>` 接回同一个 shell，复用已有的 fs 会话协议消息类型。
+    let hello = if reused {
+        fs::ServerResponse::SessionAttached {
+            id: session_id.clone(),
+            cwd: sandbox_root_path.display().to_string(),
+        }
+    } else {
+        fs::ServerResponse::SessionOpened {
+            id: session_id.clone(),
+        }
+    };
+    if sender
+        .send(Message::Text(serde_json::to_string(&hello).unwrap().into()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let mut writer = {
+        let guard = session_handle.lock().await;
+        let pty = &guard
+            .pty
+            .as_ref()
+            .expect("ensure_pty guarantees a live pty above")
+            .pty;
+        match pty.take_writer() {
+            Ok(w) => w,
+            Err(e) => {
+                tracing::error!("Failed to take pty writer for {}: {:?}", peer, e);
+                return;
+            }
+        }
+    };
+
+    // WebSocket -> PTY：异步端把输入字节丢进 channel，阻塞线程负责实际写入。
+    let (in_tx, in_rx) = std::sync::mpsc::channel::<Vec<u8>>();
+    tokio::task::spawn_blocking(move || {
+        while let Ok(data) = in_rx.recv() {
+            if writer.write_all(&data).is_err() {
+                break;
+            }
+        }
+    });
+
+    loop {
+        tokio::select! {
+            chunk = output_rx.recv() => {
+                match chunk {
+                    Ok(bytes) => {
+                        let response = WebSocketResponse {
+                            output: Some(String::from_utf8_lossy(&bytes).into_owned()),
+                            cwd_update: None,
+                        };
+                        if let Err(e) = sender
+                            .send(Message::Text(serde_json::to_string(&response).unwrap().into()))
+                            .await
+                        {
+                            tracing::error!("Failed to forward pty output to {}: {:?}", peer, e);
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        // 丢了一些历史输出，继续订阅后面的字节就好，不值得
+                        // 为此断开连接。
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        // Shell 进程退出，后台读取线程随之结束。
+                        break;
+                    }
                 }
-                Message::Close(c) => {
-                    tracing::info!("Connection closed by {}: {:?}", peer, c);
-                    break;
+            }
+            msg = receiver.next() => {
+                if matches!(msg, Some(Ok(_))) {
+                    // 客户端仍在使用这个 shell：刷新会话活跃时间，免得被 `reap_idle` 当作空闲回收。
+                    session_handle.lock().await.touch();
                 }
-                _ => {
-                    tracing::warn!("Unsupported message type from {}: {:?}", peer, msg);
+                match msg {
+                    Some(Ok(Message::Binary(data))) => {
+                        if in_tx.send(data.to_vec()).is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(Message::Text(text))) => {
+                        match serde_json::from_str::<PtyControl>(&text) {
+                            Ok(PtyControl::Resize { cols, rows }) => {
+                                let guard = session_handle.lock().await;
+                                if let Some(handle) = guard.pty.as_ref() {
+                                    if let Err(e) = handle.pty.resize(cols, rows) {
+                                        tracing::warn!("Failed to resize pty for {}: {:?}", peer, e);
+                                    }
+                                }
+                            }
+                            Err(_) => {
+                                // 非控制帧的文本当作按键输入，保持透传行为。
+                                if in_tx.send(text.into_bytes()).is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Close(c))) => {
+                        tracing::info!("PTY connection closed by {}: {:?}", peer, c);
+                        break;
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => {
+                        tracing::error!("WebSocket error for `{}`: {}", peer, e);
+                        break;
+                    }
+                    None => break,
                 }
-            },
-            Err(err) => {
-                tracing::error!("WebSocket error for `{}`: {}", peer, err);
-                break;
             }
         }
     }
-    tracing::info!("`{}` WebSocket connection closed.", peer);
+
+    // 只是这一个连接断开：shell 继续运行，供同一个 session id 以后重连，
+    // 或者等 `CloseSession`/空闲回收显式杀掉。
+    session_handle.lock().await.touch();
+    tracing::info!(
+        "`{}` PTY WebSocket connection closed (session {} left running).",
+        peer,
+        session_id
+    );
 }
 
 // **修复 5: process_command 返回 CommandResult**
@@ -219,6 +898,7 @@ fn handle_help_command() -> String {
     \x1b[32m  pwd\x1b[0m         - Prints working directory.\r\n\
     \x1b[32m  cd <path>\x1b[0m   - Change current directory.\r\n\
     \x1b[32m  ls\x1b[0m          - List directory contents.\r\n\
+    \x1b[32m  search <pat>\x1b[0m - Recursively search paths/contents (--content, --ignore-case, --glob, --max-depth, --limit).\r\n\
     \x1b[32m  whoami\x1b[0m      - Print the user name.\r\n\
     \x1b[32m  about\x1b[0m       - About this terminal.\r\n\
     \r\nCustom Commands:\r\n\
@@ -242,43 +922,20 @@ async fn handle_cd_command(args: &[&str], current_dir: &Path) -> (String, Option
         response_output = "".to_string();
     } else {
         let target_path_str = args[0];
-        let target_path = PathBuf::from(target_path_str);
-
-        let resolved_path = if target_path.is_absolute() {
-            if target_path.starts_with(&sandbox_root_path) {
-                target_path
-            } else {
-                response_output = format!(
-                    "Error: Cannot access '{}' outside the sandbox.\r\n",
-                    target_path_str
-                );
-                return (response_output, None);
-            }
-        } else {
-            current_dir.join(target_path)
-        };
-
-        match resolved_path.canonicalize() {
-            Ok(canonical_path) => {
-                if canonical_path.is_dir() && canonical_path.starts_with(&sandbox_root_path) {
-                    new_cwd_opt = Some(canonical_path);
-                    response_output = "".to_string();
-                } else if !canonical_path.starts_with(&sandbox_root_path) {
-                    response_output =
-                        "Error: Cannot access path outside the sandbox.\r\n".to_string();
-                } else {
-                    response_output = format!(
-                        "Error: '{}' is not a directory or does not exist.\r\n",
-                        target_path_str
-                    );
-                }
+        match resolve_existing_in_sandbox(target_path_str, current_dir) {
+            Ok(canonical_path) if canonical_path.is_dir() => {
+                new_cwd_opt = Some(canonical_path);
+                response_output = "".to_string();
             }
-            Err(_) => {
+            Ok(_) => {
                 response_output = format!(
-                    "Error: Path '{}' is invalid or does not exist.\r\n",
+                    "Error: '{}' is not a directory or does not exist.\r\n",
                     target_path_str
                 );
             }
+            Err(e) => {
+                response_output = format!("Error: {}\r\n", e);
+            }
         }
     }
     (response_output, new_cwd_opt)
@@ -377,3 +1034,90 @@ fn handle_heyeuuu_command(args: &[&str]) -> String {
 fn handle_creeper_command() -> String {
     "Sss... Boom! (just kidding, I'm friendly) \r\n".to_string()
 }
+
+#[cfg(test)]
+mod sandbox_tests {
+    use super::*;
+
+    /// Fresh, empty directory under `SANDBOX_ROOT` for a single test to use
+    /// as its `current_dir`/fixture area. Cleaned up by the caller.
+    fn fixture_dir(name: &str) -> PathBuf {
+        let dir = PathBuf::from(SANDBOX_ROOT).join(format!("test-fixture-{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("failed to create sandbox fixture dir");
+        dir
+    }
+
+    #[test]
+    fn resolve_existing_rejects_dotdot_escape() {
+        let dir = fixture_dir("existing-dotdot");
+        let result = resolve_existing_in_sandbox("../../../../../../etc/passwd", &dir);
+        assert!(result.is_err(), "expected '..' escape to be rejected, got {:?}", result);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_existing_rejects_absolute_escape() {
+        let dir = fixture_dir("existing-absolute");
+        let result = resolve_existing_in_sandbox("/etc/passwd", &dir);
+        assert!(result.is_err(), "expected absolute escape to be rejected, got {:?}", result);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_new_rejects_dotdot_escape() {
+        let dir = fixture_dir("new-dotdot");
+        let result = resolve_new_in_sandbox("../../../../../../tmp/evil.txt", &dir);
+        assert!(result.is_err(), "expected '..' escape to be rejected, got {:?}", result);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_new_rejects_dangling_symlink_escape() {
+        let dir = fixture_dir("new-dangling-symlink");
+        let outside_target = std::env::temp_dir().join("web_shell_sandbox_escape_target.txt");
+        let _ = std::fs::remove_file(&outside_target);
+        let link = dir.join("evil_link");
+        std::os::unix::fs::symlink(&outside_target, &link).expect("failed to create test symlink");
+
+        // `outside_target` doesn't exist yet: this is the exact shape of the
+        // escape the comment on `resolve_new_in_sandbox` describes, where
+        // `Path::exists()` would report the dangling link as absent and the
+        // walk would skip straight past it.
+        let result = resolve_new_in_sandbox("evil_link", &dir);
+        assert!(result.is_err(), "dangling symlink escape must be rejected, got {:?}", result);
+
+        let _ = std::fs::remove_file(&link);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_new_rejects_symlink_escape_to_existing_outside_target() {
+        let dir = fixture_dir("new-existing-symlink");
+        let outside_target = std::env::temp_dir().join("web_shell_sandbox_escape_existing.txt");
+        std::fs::write(&outside_target, b"outside").expect("failed to create outside target");
+        let link = dir.join("evil_link");
+        std::os::unix::fs::symlink(&outside_target, &link).expect("failed to create test symlink");
+
+        let result = resolve_new_in_sandbox("evil_link", &dir);
+        assert!(result.is_err(), "symlink to an existing outside target must be rejected, got {:?}", result);
+
+        let _ = std::fs::remove_file(&outside_target);
+        let _ = std::fs::remove_file(&link);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_new_allows_symlink_pointing_inside_sandbox() {
+        let dir = fixture_dir("new-inside-symlink");
+        let real_target = dir.join("real.txt");
+        std::fs::write(&real_target, b"hi").expect("failed to create real target");
+        let link = dir.join("inside_link");
+        std::os::unix::fs::symlink(&real_target, &link).expect("failed to create test symlink");
+
+        let result = resolve_new_in_sandbox("inside_link", &dir);
+        assert!(result.is_ok(), "symlink resolving inside the sandbox should be allowed, got {:?}", result);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}